@@ -0,0 +1,128 @@
+//! Per-PID signal escalation state machine.
+//!
+//! Tracks, for each PID the reaper has signaled, which signal in its chain
+//! was sent most recently and when, so the monitoring loop can escalate to
+//! the next signal once the configured grace period elapses without the
+//! process exiting.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use sysinfo::{Pid, Signal};
+
+/// Parses a signal name (case-insensitive, `SIG` prefix optional) into a
+/// [`sysinfo::Signal`]. Only the signals relevant to process termination are
+/// supported.
+pub fn parse_signal(s: &str) -> Result<Signal, String> {
+    let name = s.trim().to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    match name {
+        "HUP" => Ok(Signal::Hangup),
+        "INT" => Ok(Signal::Interrupt),
+        "QUIT" => Ok(Signal::Quit),
+        "TERM" => Ok(Signal::Term),
+        "KILL" => Ok(Signal::Kill),
+        "USR1" => Ok(Signal::User1),
+        "USR2" => Ok(Signal::User2),
+        "ALRM" => Ok(Signal::Alarm),
+        "CONT" => Ok(Signal::Continue),
+        "STOP" => Ok(Signal::Stop),
+        other => Err(format!("Unsupported signal name: {}", other)),
+    }
+}
+
+/// Escalation state for a single PID that has been signaled at least once.
+pub struct PidState {
+    chain: Vec<Signal>,
+    sent_index: usize,
+    last_signal_at: Instant,
+}
+
+impl PidState {
+    /// Creates state for a PID that has just received `chain[0]`.
+    pub fn new(chain: Vec<Signal>, signaled_at: Instant) -> PidState {
+        PidState { chain, sent_index: 0, last_signal_at: signaled_at }
+    }
+
+    pub fn last_signal_at(&self) -> Instant {
+        self.last_signal_at
+    }
+
+    /// Whether the final signal in the chain has already been sent.
+    pub fn is_exhausted(&self) -> bool {
+        self.sent_index + 1 >= self.chain.len()
+    }
+
+    /// Advances to the next signal in the chain, if any remain, records it as
+    /// the most recently sent signal, and returns it for the caller to send.
+    pub fn escalate(&mut self, at: Instant) -> Option<Signal> {
+        if self.is_exhausted() {
+            return None;
+        }
+        self.sent_index += 1;
+        self.last_signal_at = at;
+        Some(self.chain[self.sent_index])
+    }
+}
+
+pub type PidStates = HashMap<Pid, PidState>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_signal_accepts_name_with_or_without_sig_prefix_case_insensitively() {
+        assert_eq!(parse_signal("TERM").unwrap(), Signal::Term);
+        assert_eq!(parse_signal("SIGTERM").unwrap(), Signal::Term);
+        assert_eq!(parse_signal("sigterm").unwrap(), Signal::Term);
+        assert_eq!(parse_signal("  kill  ").unwrap(), Signal::Kill);
+    }
+
+    #[test]
+    fn parse_signal_rejects_unknown_name() {
+        assert!(parse_signal("NOTASIGNAL").is_err());
+    }
+
+    #[test]
+    fn pid_state_escalates_through_the_chain_in_order() {
+        let chain = vec![Signal::Term, Signal::Quit, Signal::Kill];
+        let t0 = Instant::now();
+        let mut state = PidState::new(chain, t0);
+
+        assert!(!state.is_exhausted());
+        assert_eq!(state.last_signal_at(), t0);
+
+        let t1 = t0 + Duration::from_secs(1);
+        assert_eq!(state.escalate(t1), Some(Signal::Quit));
+        assert_eq!(state.last_signal_at(), t1);
+        assert!(!state.is_exhausted());
+
+        let t2 = t1 + Duration::from_secs(1);
+        assert_eq!(state.escalate(t2), Some(Signal::Kill));
+        assert_eq!(state.last_signal_at(), t2);
+        assert!(state.is_exhausted());
+    }
+
+    #[test]
+    fn pid_state_escalate_is_a_noop_once_exhausted() {
+        let mut state = PidState::new(vec![Signal::Term, Signal::Kill], Instant::now());
+
+        let kill_sent_at = Instant::now() + Duration::from_secs(1);
+        assert_eq!(state.escalate(kill_sent_at), Some(Signal::Kill));
+        assert!(state.is_exhausted());
+
+        // A no-op escalation shouldn't bump `last_signal_at`, or callers that
+        // key a retry/backoff off it would see a deadline that keeps moving.
+        let after_exhausted = kill_sent_at + Duration::from_secs(1);
+        assert_eq!(state.escalate(after_exhausted), None);
+        assert_eq!(state.last_signal_at(), kill_sent_at);
+    }
+
+    #[test]
+    fn single_signal_chain_is_exhausted_immediately() {
+        let state = PidState::new(vec![Signal::Kill], Instant::now());
+        assert!(state.is_exhausted());
+    }
+}
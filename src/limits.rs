@@ -0,0 +1,199 @@
+//! Multi-metric resource limits.
+//!
+//! A process is reaped once it breaches ANY configured limit. I/O write
+//! rate can only be computed across two samples, so callers pass in (and
+//! this module updates) a per-PID map of the last observed cumulative
+//! written-bytes count and when it was taken.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use byte_unit::Byte;
+use sysinfo::{Pid, Process};
+
+/// The metric that caused a process to be reaped, with a human-readable
+/// description suitable for logging.
+pub struct Breach {
+    pub metric: &'static str,
+    pub message: String,
+}
+
+/// The set of configured limits; any limit left `None` is never checked.
+pub struct Limits {
+    pub memory: Option<u64>,
+    pub virtual_memory: Option<u64>,
+    pub cpu_percent: Option<f32>,
+    pub io_write_rate: Option<u64>,
+}
+
+impl Limits {
+    /// Checks `process` against every configured limit and returns the first
+    /// one breached, if any. `effective_memory` is the RSS to compare against
+    /// the memory limit; pass `process.memory()` normally, or a summed
+    /// subtree RSS under `--sum-tree`. `io_samples` carries each PID's last
+    /// observed (timestamp, total written bytes) so the write rate can be
+    /// computed across two refreshes.
+    pub fn check(&self, process: &Process, effective_memory: u64, io_samples: &mut HashMap<Pid, (Instant, u64)>) -> Option<Breach> {
+        let io_write_rate = match self.io_write_rate {
+            Some(_) => self.write_rate(process, io_samples),
+            None => None,
+        };
+
+        self.check_metrics(effective_memory, process.virtual_memory(), process.cpu_usage(), io_write_rate)
+    }
+
+    /// The actual precedence/threshold logic behind [`Limits::check`], pulled
+    /// out so it can be unit-tested against plain numbers instead of a live
+    /// `sysinfo::Process`. Checks memory, then virtual memory, then CPU, then
+    /// I/O write rate, returning the first breach found.
+    fn check_metrics(&self, memory: u64, virtual_memory: u64, cpu_percent: f32, io_write_rate: Option<u64>) -> Option<Breach> {
+        if let Some(limit) = self.memory {
+            if memory >= limit {
+                return Some(Breach {
+                    metric: "memory",
+                    message: format!("memory usage of {} >= limit of {}", fmt_bytes(memory), fmt_bytes(limit)),
+                });
+            }
+        }
+
+        if let Some(limit) = self.virtual_memory {
+            if virtual_memory >= limit {
+                return Some(Breach {
+                    metric: "virtual memory",
+                    message: format!("virtual memory usage of {} >= limit of {}", fmt_bytes(virtual_memory), fmt_bytes(limit)),
+                });
+            }
+        }
+
+        if let Some(limit) = self.cpu_percent {
+            if cpu_percent >= limit {
+                return Some(Breach {
+                    metric: "cpu",
+                    message: format!("CPU usage of {:.1}% >= limit of {:.1}%", cpu_percent, limit),
+                });
+            }
+        }
+
+        if let Some(limit) = self.io_write_rate {
+            if let Some(rate) = io_write_rate {
+                if rate >= limit {
+                    return Some(Breach {
+                        metric: "I/O write rate",
+                        message: format!("I/O write rate of {}/s >= limit of {}/s", fmt_bytes(rate), fmt_bytes(limit)),
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Computes bytes/sec written since the last sample of this PID, if one
+    /// exists, and records the current sample for next time.
+    fn write_rate(&self, process: &Process, io_samples: &mut HashMap<Pid, (Instant, u64)>) -> Option<u64> {
+        let now = Instant::now();
+        let total_written = process.disk_usage().total_written_bytes;
+        let previous = io_samples.insert(process.pid(), (now, total_written));
+        rate_from_samples(previous, (now, total_written))
+    }
+}
+
+/// The byte-delta/time-delta math behind [`Limits::write_rate`], pulled out
+/// so it can be unit-tested against plain (timestamp, bytes) pairs instead
+/// of a live `sysinfo::Process`.
+fn rate_from_samples(previous: Option<(Instant, u64)>, current: (Instant, u64)) -> Option<u64> {
+    let (prev_time, prev_written) = previous?;
+    let (now, total_written) = current;
+    let elapsed = now.saturating_duration_since(prev_time).as_secs_f64();
+    if elapsed <= 0.0 || total_written < prev_written {
+        return None;
+    }
+
+    Some(((total_written - prev_written) as f64 / elapsed) as u64)
+}
+
+fn fmt_bytes(bytes: u64) -> String {
+    Byte::from_u64(bytes)
+        .get_appropriate_unit(byte_unit::UnitType::Binary)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn all_limits() -> Limits {
+        Limits {
+            memory: Some(100),
+            virtual_memory: Some(200),
+            cpu_percent: Some(50.0),
+            io_write_rate: Some(1000),
+        }
+    }
+
+    #[test]
+    fn no_limits_configured_never_breaches() {
+        let limits = Limits { memory: None, virtual_memory: None, cpu_percent: None, io_write_rate: None };
+        assert!(limits.check_metrics(u64::MAX, u64::MAX, f32::MAX, Some(u64::MAX)).is_none());
+    }
+
+    #[test]
+    fn under_every_limit_is_no_breach() {
+        let limits = all_limits();
+        assert!(limits.check_metrics(99, 199, 49.0, Some(999)).is_none());
+    }
+
+    #[test]
+    fn memory_takes_precedence_over_every_other_metric() {
+        let limits = all_limits();
+        let breach = limits.check_metrics(100, 200, 50.0, Some(1000)).unwrap();
+        assert_eq!(breach.metric, "memory");
+    }
+
+    #[test]
+    fn virtual_memory_breach_checked_when_memory_is_fine() {
+        let limits = all_limits();
+        let breach = limits.check_metrics(0, 200, 0.0, None).unwrap();
+        assert_eq!(breach.metric, "virtual memory");
+    }
+
+    #[test]
+    fn cpu_breach_checked_when_memory_and_virtual_memory_are_fine() {
+        let limits = all_limits();
+        let breach = limits.check_metrics(0, 0, 50.0, None).unwrap();
+        assert_eq!(breach.metric, "cpu");
+    }
+
+    #[test]
+    fn io_write_rate_breach_checked_last() {
+        let limits = all_limits();
+        let breach = limits.check_metrics(0, 0, 0.0, Some(1000)).unwrap();
+        assert_eq!(breach.metric, "I/O write rate");
+    }
+
+    #[test]
+    fn io_write_rate_limit_set_but_no_sample_yet_is_no_breach() {
+        let limits = all_limits();
+        assert!(limits.check_metrics(0, 0, 0.0, None).is_none());
+    }
+
+    #[test]
+    fn rate_from_samples_is_none_without_a_previous_sample() {
+        assert_eq!(rate_from_samples(None, (Instant::now(), 500)), None);
+    }
+
+    #[test]
+    fn rate_from_samples_divides_byte_delta_by_time_delta() {
+        let t0 = Instant::now();
+        let t1 = t0 + Duration::from_secs(2);
+        assert_eq!(rate_from_samples(Some((t0, 1000)), (t1, 3000)), Some(1000));
+    }
+
+    #[test]
+    fn rate_from_samples_is_none_on_counter_rollback() {
+        let t0 = Instant::now();
+        let t1 = t0 + Duration::from_secs(1);
+        assert_eq!(rate_from_samples(Some((t0, 1000)), (t1, 500)), None);
+    }
+}
@@ -1,11 +1,17 @@
-use std::{io::Write, error::Error, fmt, collections::LinkedList, thread, time, sync::{Arc, atomic::AtomicBool}};
+use std::{io::Write, error::Error, fmt, collections::LinkedList, path::PathBuf, thread, time::{Duration, Instant}, sync::{Arc, atomic::AtomicBool}};
 use clap::Parser;
 use byte_unit::Byte;
 use log::{debug, error, info, warn};
-use sysinfo::{Pid, System, MemoryRefreshKind};
-use signal_hook::{consts::SIGTERM, iterator::Signals};
+use sysinfo::{Pid, System, MemoryRefreshKind, ProcessRefreshKind, ProcessesToUpdate, UpdateKind};
+use signal_hook::{consts::{SIGHUP, SIGTERM}, iterator::Signals};
 use libsystemd::daemon;
 
+mod escalation;
+mod limits;
+mod pidfd;
+mod proctree;
+mod rules;
+
 #[derive(Debug)]
 struct ProcessReaperError {
     text: String
@@ -28,13 +34,18 @@ impl fmt::Display for ProcessReaperError {
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    /// Name of process(es) to monitor
+    /// Name of process(es) to monitor; ignored if --config is given
     #[clap(short, long)]
-    process_name: String,
+    process_name: Option<String>,
 
-    /// Memory limit; if exceeded, offending process will be killed
+    /// Memory limit; if exceeded, offending process will be killed; ignored if --config is given
     #[clap(short, long)]
-    memory_limit: String,
+    memory_limit: Option<String>,
+
+    /// Load a set of independent monitoring rules from a TOML or JSON file, instead of
+    /// watching a single process via --process-name/--memory-limit
+    #[clap(long)]
+    config: Option<PathBuf>,
 
     /// Use syslog
     #[clap(long, action)]
@@ -42,7 +53,89 @@ struct Args {
 
     /// Notify systemd for watchdog compatibility
     #[clap(long, action)]
-    systemd_notify: bool
+    systemd_notify: bool,
+
+    /// Time to wait after sending a signal before escalating to the next one in the chain
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "2s")]
+    grace_period: Duration,
+
+    /// Interval between scans for newly offending processes
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "2s")]
+    poll_interval: Duration,
+
+    /// First signal sent to a process that exceeds its limit; ignored if --escalation-signal is given
+    #[clap(long, value_parser = escalation::parse_signal, default_value = "TERM")]
+    term_signal: sysinfo::Signal,
+
+    /// Signal sent if a process is still alive after the grace period; ignored if --escalation-signal is given
+    #[clap(long, value_parser = escalation::parse_signal, default_value = "KILL")]
+    kill_signal: sysinfo::Signal,
+
+    /// Full escalation chain, one signal per grace period, e.g. repeat this flag as
+    /// `--escalation-signal INT --escalation-signal QUIT --escalation-signal KILL`; overrides
+    /// --term-signal/--kill-signal if given
+    #[clap(long, value_parser = escalation::parse_signal)]
+    escalation_signal: Vec<sysinfo::Signal>,
+
+    /// Virtual memory limit; same syntax as --memory-limit
+    #[clap(long)]
+    virtual_memory_limit: Option<String>,
+
+    /// CPU usage limit, in percent (can exceed 100% for multi-threaded processes)
+    #[clap(long)]
+    cpu_limit: Option<f32>,
+
+    /// I/O write rate limit, e.g. "10MB" for 10MB/s
+    #[clap(long)]
+    io_write_rate_limit: Option<String>,
+
+    /// Signal a matched process's whole subtree (descendants first, then the process itself) instead of just the process
+    #[clap(long, action)]
+    kill_tree: bool,
+
+    /// Compare the memory limit against the summed RSS of a matched process plus all of its descendants
+    #[clap(long, action)]
+    sum_tree: bool,
+}
+
+/// The per-process data the reaper needs refreshed every loop iteration:
+/// memory for the existing limit, CPU and disk usage for the newer ones, and
+/// the command line so rule files can match on `cmdline_contains` (argv is
+/// opt-in in sysinfo and defaults to never being fetched).
+fn process_refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::new()
+        .with_memory()
+        .with_cpu()
+        .with_disk_usage()
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+}
+
+/// Sends `signal` to `process_name`'s `pid` and every PID in `subtree`.
+/// `subtree` is the descendant set captured once, when the rule first
+/// signaled `pid` — a descendant whose parent has already exited is
+/// reparented to init, so `process.parent()` can no longer rediscover it on
+/// a later escalation; the caller must carry the original set forward
+/// instead of re-deriving it here.
+fn signal_process(sys: &System, process_name: &str, pid: Pid, subtree: &[Pid], signal: sysinfo::Signal) {
+    for &descendant_pid in subtree {
+        if let Some(descendant) = sys.process(descendant_pid) {
+            debug!("{} ({}) descendant of {}; signaling as part of subtree", process_name, descendant_pid, pid);
+            send_signal(descendant, process_name, signal);
+        }
+    }
+
+    if let Some(process) = sys.process(pid) {
+        send_signal(process, process_name, signal);
+    }
+}
+
+fn send_signal(process: &sysinfo::Process, process_name: &str, signal: sysinfo::Signal) {
+    let sig_sent = process.kill_with(signal)
+        .expect("Configured signal doesn't exist on this system");
+
+    if !sig_sent {
+        error!("Failed to send {:?} to {} ({})", signal, process_name, process.pid());
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -50,85 +143,166 @@ fn main() -> Result<(), Box<dyn Error>> {
     init_logging(args.syslog);
 
     info!("Initializing");
-    let (should_run, mut sys, mem_limit) = initialize(&args.memory_limit)
+    let (should_run, reload_requested, mut sys) = initialize()
         .expect("Failed to initialize");
 
-    info!("Entering monitoring loop; target process: {}", args.process_name);
+    let pidfd_supported = pidfd::probe_support();
+    if !pidfd_supported {
+        warn!("pidfd_open(2) unavailable on this kernel; falling back to poll-based exit detection");
+    }
+
+    let mut rule_set = build_rule_set(&args, &sys)?;
+
+    // `cpu_usage()` is only meaningful across two refreshes of the same
+    // process, so take one now to seed the baseline before the first scan.
+    // From here on the loop refreshes exactly once per iteration (after the
+    // wait, below) so that baseline and every later one are poll_interval
+    // (or grace-period) apart, not back-to-back.
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, process_refresh_kind());
+
+    info!("Entering monitoring loop; {} rule(s) active", rule_set.len());
     if args.systemd_notify {
         debug!("Notifying systemd that daemon is ready");
         let _ = daemon::notify(
             false,
             &[
                 daemon::NotifyState::Ready,
-                daemon::NotifyState::Status(format!("Monitoring {} (limit {})", args.process_name, args.memory_limit).into()),
+                daemon::NotifyState::Status("Monitoring started".to_string()),
             ],
         );
     }
 
+    // The watchdog ping cadence is derived from systemd's WATCHDOG_USEC; if
+    // the configured poll interval is too coarse to satisfy it, pet the
+    // watchdog from a dedicated timer thread instead of inline in the
+    // monitoring loop, so a slow refresh_processes_specifics() can't trip it.
+    let mut watchdog_owned_by_timer = false;
+    if args.systemd_notify {
+        if let Some(watchdog_timeout) = daemon::watchdog_enabled(false) {
+            let ping_interval = watchdog_timeout / 2;
+            if args.poll_interval > ping_interval {
+                info!("Poll interval exceeds half the watchdog timeout; petting watchdog from a dedicated timer thread every {:?}", ping_interval);
+                watchdog_owned_by_timer = true;
+                let should_run = Arc::clone(&should_run);
+                thread::spawn(move || {
+                    while should_run.load(std::sync::atomic::Ordering::Relaxed) {
+                        thread::sleep(ping_interval);
+                        let _ = daemon::notify(false, &[daemon::NotifyState::Watchdog]);
+                    }
+                });
+            }
+        }
+    }
+
     let mut loop_number = 0_u64;
     while should_run.load(std::sync::atomic::Ordering::Relaxed) {
         debug!("Starting loop #{}", loop_number);
         loop_number += 1;
 
-        let mut termed_pids: LinkedList<Pid> = LinkedList::new();
-        sys.refresh_all();
-        let target_processes = sys.processes_by_exact_name(&args.process_name);
-        for process in target_processes {
-            let mem_usage = process.memory();
-            let pid = process.pid();
-            let mem_usage_str = Byte::from_u64(mem_usage)
-                .get_appropriate_unit(byte_unit::UnitType::Binary)
-                .to_string();
-
-            if mem_usage >= mem_limit {
-                warn!("{} ({}) memory usage of {} greater than threshold of {}; terminating", 
-                    args.process_name, pid, mem_usage_str, args.memory_limit);
-                let sig_sent = 
-                    process.kill_with(sysinfo::Signal::Term)
-                        .expect("sysinfo::Signal::Term signal doesn't exist on this system");
-
-                if !sig_sent {
-                    error!("Failed to send sysinfo::Signal::Term to {} ({})", args.process_name, pid);
+        if reload_requested.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            info!("Reloading configuration");
+            if args.systemd_notify {
+                let _ = daemon::notify(false, &[daemon::NotifyState::Reloading]);
+            }
+            match build_rule_set(&args, &sys) {
+                Ok(mut reloaded) => {
+                    rules::carry_over_state(&mut reloaded, rule_set);
+                    rule_set = reloaded;
+                    info!("Reloaded {} rule(s)", rule_set.len());
                 }
-
-                termed_pids.push_back(pid);
+                Err(e) => error!("Failed to reload configuration: {}; keeping previous rules", e),
             }
-            else {
-                debug!("{} ({}) using {} of memory", args.process_name, pid, mem_usage_str);
+            if args.systemd_notify {
+                let _ = daemon::notify(false, &[daemon::NotifyState::Ready]);
             }
         }
 
-        let sleep_dur = time::Duration::from_secs(2);
-        debug!("Sleeping for {} seconds", sleep_dur.as_secs_f32());
-        thread::sleep(sleep_dur);
-
-        sys.refresh_all();
-        for pid in termed_pids {
-            if let Some(process) = sys.process(pid) {
-                warn!("Terminated process, {} ({}), still alive; killing", args.process_name, pid);
-                let sig_sent = 
-                    process.kill_with(sysinfo::Signal::Kill)
-                        .expect("sysinfo::Signal::Kill signal doesn't exist on this system");
+        // No refresh here: `sys` already holds the snapshot taken after the
+        // wait at the end of the previous iteration (or the seed refresh
+        // above, on the first iteration). Refreshing a second time per loop
+        // would make `cpu_usage()` measure the few microseconds of loop
+        // overhead between the two refreshes instead of the actual
+        // poll_interval/grace-period window CPU time accrues over.
+        for rule in rule_set.iter_mut() {
+            rule.scan(&sys);
+        }
 
-                if !sig_sent {
-                    error!("Failed to send sysinfo::Signal::Kill to {} ({})", args.process_name, pid);
+        let now = Instant::now();
+        let mut wake_at = now + args.poll_interval;
+        let mut signaled_pids: LinkedList<Pid> = LinkedList::new();
+        for rule in rule_set.iter() {
+            if let Some(deadline) = rule.earliest_deadline() {
+                if deadline < wake_at {
+                    wake_at = deadline;
                 }
             }
-            else {
-                debug!("Could not find {} ({}) again; assuming successful termination", args.process_name, pid)
-            }
+            signaled_pids.extend(rule.tracked_pids());
+        }
+
+        if signaled_pids.is_empty() {
+            thread::sleep(args.poll_interval);
+        }
+        else if pidfd_supported {
+            pidfd::wait_for_exit(&signaled_pids, wake_at);
+        }
+        else {
+            thread::sleep(wake_at.saturating_duration_since(Instant::now()));
+        }
+
+        sys.refresh_processes_specifics(ProcessesToUpdate::All, true, process_refresh_kind());
+        for rule in rule_set.iter_mut() {
+            rule.escalate_and_reap(&sys);
         }
 
         if args.systemd_notify {
-            debug!("Petting systemd watchdog");
-            daemon::notify(false, &[daemon::NotifyState::Watchdog])
-                .expect("Failed to pet systemd watchdog");
+            let status = rule_set.iter().map(|rule| rule.status_summary()).collect::<Vec<_>>().join("; ");
+            let mut notify_state = vec![daemon::NotifyState::Status(status)];
+            if !watchdog_owned_by_timer {
+                debug!("Petting systemd watchdog");
+                notify_state.push(daemon::NotifyState::Watchdog);
+            }
+            daemon::notify(false, &notify_state)
+                .expect("Failed to notify systemd");
         }
     }
 
     Ok(())
 }
 
+/// Builds the active rule set from either `--config` or the legacy
+/// single-process flags, so both the initial load and a SIGHUP reload share
+/// the same logic.
+fn build_rule_set(args: &Args, sys: &System) -> Result<Vec<rules::Rule>, Box<dyn Error>> {
+    match &args.config {
+        Some(path) => rules::load_rules(path, sys),
+        None => {
+            let process_name = args.process_name.clone()
+                .ok_or_else(|| ProcessReaperError::new("--process-name is required unless --config is given"))?;
+            let memory_limit = args.memory_limit.as_deref()
+                .ok_or_else(|| ProcessReaperError::new("--memory-limit is required unless --config is given"))?;
+
+            let signal_chain = if args.escalation_signal.is_empty() {
+                vec![args.term_signal, args.kill_signal]
+            } else {
+                args.escalation_signal.clone()
+            };
+
+            Ok(vec![rules::Rule::from_single_process_args(
+                process_name,
+                memory_limit,
+                args.virtual_memory_limit.as_deref(),
+                args.cpu_limit,
+                args.io_write_rate_limit.as_deref(),
+                args.grace_period,
+                signal_chain,
+                args.kill_tree,
+                args.sum_tree,
+                sys,
+            )?])
+        }
+    }
+}
+
 fn init_logging(use_syslog: bool) {
     let mut log_builder = env_logger::Builder::from_env(
         env_logger::Env::default().default_filter_or("info"));
@@ -142,25 +316,33 @@ fn init_logging(use_syslog: bool) {
     log_builder.init();
 }
 
-fn initialize(memory_limit: &str) -> Result<(Arc<AtomicBool>, System, u64), Box<dyn Error>> {
+fn initialize() -> Result<(Arc<AtomicBool>, Arc<AtomicBool>, System), Box<dyn Error>> {
     let should_run = Arc::new(AtomicBool::new(true));
+    let reload_requested = Arc::new(AtomicBool::new(false));
     let should_run_arc_clone = Arc::clone(&should_run);
+    let reload_requested_arc_clone = Arc::clone(&reload_requested);
 
-    let mut signals = Signals::new([SIGTERM])?;
+    let mut signals = Signals::new([SIGTERM, SIGHUP])?;
     thread::spawn(move || {
         let should_run = should_run_arc_clone;
+        let reload_requested = reload_requested_arc_clone;
         for sig in signals.forever() {
-            warn!("Received signal {:?}", sig);
-            should_run.store(false, std::sync::atomic::Ordering::Relaxed);
+            if sig == SIGHUP {
+                reload_requested.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+            else {
+                warn!("Received signal {:?}; shutting down", sig);
+                should_run.store(false, std::sync::atomic::Ordering::Relaxed);
+            }
         }
     });
 
     let mut sys = System::new();
-    
+
     sys.refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
-    let mem_limit = str_to_bytes_of_memory(memory_limit, &sys)?;
+    sys.refresh_users_list();
 
-    Ok((should_run, sys, mem_limit))
+    Ok((should_run, reload_requested, sys))
 }
 
 fn str_to_bytes_of_memory(mem_str: &str, sys: &System) -> Result<u64, Box<dyn Error>> {
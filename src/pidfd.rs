@@ -0,0 +1,172 @@
+//! Linux `pidfd`-based exit detection.
+//!
+//! A pidfd becomes readable exactly when the process it refers to exits, so
+//! polling on it lets the reaper learn about a termination the instant it
+//! happens instead of sleeping for a fixed grace period and hoping. Kernels
+//! older than 5.3 (or sandboxes without `CONFIG_PIDFD`) don't implement
+//! `pidfd_open(2)`; callers should fall back to the refresh-and-check polling
+//! path when [`probe_support`] returns `false`.
+
+use std::collections::LinkedList;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Instant;
+
+use log::warn;
+use sysinfo::Pid;
+
+/// An open pidfd for a single process, closed automatically on drop.
+struct PidFd(RawFd);
+
+impl PidFd {
+    fn open(pid: Pid) -> io::Result<PidFd> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_u32() as libc::pid_t, 0) };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(PidFd(fd as RawFd))
+        }
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+/// Probes whether this kernel supports `pidfd_open(2)` by opening a pidfd on
+/// our own PID. Intended to be called once at startup; the result should be
+/// cached by the caller rather than re-probed every loop iteration.
+pub fn probe_support() -> bool {
+    match PidFd::open(Pid::from_u32(std::process::id())) {
+        Ok(_) => true,
+        Err(e) => {
+            debug_assert!(e.raw_os_error().is_some());
+            !matches!(e.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EINVAL))
+        }
+    }
+}
+
+/// Waits, via `poll(2)` on each PID's pidfd, until every process in `pids`
+/// has exited or `deadline` passes. Which PIDs are actually still alive
+/// afterwards is determined by the caller's next process-table refresh, not
+/// by this function — it only blocks so that an exit is noticed as soon as
+/// it happens instead of at the next poll_interval-paced wakeup.
+pub fn wait_for_exit(pids: &LinkedList<Pid>, deadline: Instant) {
+    let mut waiters: Vec<(Pid, PidFd)> = Vec::new();
+
+    for &pid in pids {
+        match PidFd::open(pid) {
+            Ok(pidfd) => waiters.push((pid, pidfd)),
+            Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {
+                // Already exited and reaped before we could open a pidfd.
+            }
+            Err(e) => {
+                warn!("Failed to open pidfd for {}: {}; will re-check after grace period", pid, e);
+            }
+        }
+    }
+
+    while !waiters.is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        let mut pollfds: Vec<libc::pollfd> = waiters
+            .iter()
+            .map(|(_, pidfd)| libc::pollfd { fd: pidfd.0, events: libc::POLLIN, revents: 0 })
+            .collect();
+        let timeout_ms = remaining.as_millis().min(i32::MAX as u128) as i32;
+
+        let ready = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout_ms) };
+        match ready {
+            0 => break,
+            n if n < 0 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                warn!("poll() on pidfds failed: {}; treating remaining PIDs as still alive", err);
+                break;
+            }
+            _ => {
+                let mut i = 0;
+                while i < waiters.len() {
+                    if pollfds[i].revents & libc::POLLIN != 0 {
+                        waiters.remove(i);
+                        pollfds.remove(i);
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::time::Duration;
+
+    #[test]
+    fn probe_support_does_not_panic() {
+        // Not asserting a specific value — just that opening a pidfd on our
+        // own PID and classifying the result doesn't panic on this kernel.
+        let _ = probe_support();
+    }
+
+    #[test]
+    fn wait_for_exit_returns_promptly_once_the_process_exits() {
+        if !probe_support() {
+            return;
+        }
+
+        let mut child = Command::new("sh").args(["-c", "sleep 0.05"]).spawn().unwrap();
+        let pids = LinkedList::from([Pid::from_u32(child.id())]);
+
+        let started = Instant::now();
+        wait_for_exit(&pids, started + Duration::from_secs(10));
+        assert!(started.elapsed() < Duration::from_secs(2),
+            "should notice the exit via poll(2), not wait out the full deadline");
+
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn wait_for_exit_stops_at_the_deadline_for_a_long_lived_process() {
+        if !probe_support() {
+            return;
+        }
+
+        let mut child = Command::new("sh").args(["-c", "sleep 10"]).spawn().unwrap();
+        let pids = LinkedList::from([Pid::from_u32(child.id())]);
+
+        let started = Instant::now();
+        wait_for_exit(&pids, started + Duration::from_millis(200));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(150) && elapsed < Duration::from_secs(2),
+            "should return once the deadline passes rather than blocking on a still-alive process, got {:?}", elapsed);
+
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn wait_for_exit_returns_immediately_for_an_already_reaped_pid() {
+        let mut child = Command::new("sh").args(["-c", "true"]).spawn().unwrap();
+        let pid = Pid::from_u32(child.id());
+        let _ = child.wait();
+
+        let pids = LinkedList::from([pid]);
+        let started = Instant::now();
+        wait_for_exit(&pids, started + Duration::from_secs(10));
+        assert!(started.elapsed() < Duration::from_secs(2),
+            "an already-exited PID should hit the ESRCH path and not wait at all");
+    }
+}
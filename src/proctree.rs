@@ -0,0 +1,111 @@
+//! Process subtree discovery, for `--kill-tree` and `--sum-tree`.
+//!
+//! A matched process often isn't the one holding the memory or doing the
+//! work — a shell or supervisor spawns the real workers as children. These
+//! helpers walk the full process table via `process.parent()` to find every
+//! transitive descendant of a PID.
+
+use std::collections::{HashMap, VecDeque};
+
+use sysinfo::{Pid, System};
+
+/// Returns every transitive descendant of `root`, ordered deepest-generation
+/// first so that, when signaled in this order followed by `root` itself,
+/// children are always signaled before their ancestors.
+pub fn descendants(sys: &System, root: Pid) -> Vec<Pid> {
+    let mut children_of: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for (&pid, process) in sys.processes() {
+        if let Some(parent) = process.parent() {
+            children_of.entry(parent).or_default().push(pid);
+        }
+    }
+
+    descendants_of(&children_of, root)
+}
+
+/// The BFS-by-generation walk behind [`descendants`], pulled out so it can be
+/// unit-tested against a plain parent/child map instead of a live process
+/// table.
+fn descendants_of(children_of: &HashMap<Pid, Vec<Pid>>, root: Pid) -> Vec<Pid> {
+    let mut by_generation = Vec::new();
+    let mut frontier = VecDeque::from([root]);
+    while !frontier.is_empty() {
+        let mut next_frontier = VecDeque::new();
+        for pid in frontier {
+            if let Some(children) = children_of.get(&pid) {
+                next_frontier.extend(children.iter().copied());
+            }
+        }
+        if next_frontier.is_empty() {
+            break;
+        }
+        by_generation.push(next_frontier.iter().copied().collect::<Vec<_>>());
+        frontier = next_frontier;
+    }
+
+    by_generation.into_iter().rev().flatten().collect()
+}
+
+/// Sums the RSS of `root` plus every transitive descendant of `root`.
+pub fn subtree_memory(sys: &System, root: Pid) -> u64 {
+    let root_memory = sys.process(root).map(|process| process.memory()).unwrap_or(0);
+    let descendant_memories = descendants(sys, root).into_iter()
+        .filter_map(|pid| sys.process(pid))
+        .map(|process| process.memory());
+    sum_memory(root_memory, descendant_memories)
+}
+
+/// The summation behind [`subtree_memory`], pulled out so it can be
+/// unit-tested against plain numbers instead of live processes.
+fn sum_memory(root_memory: u64, descendant_memories: impl Iterator<Item = u64>) -> u64 {
+    descendant_memories.fold(root_memory, |total, memory| total + memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn children_map(edges: &[(u32, u32)]) -> HashMap<Pid, Vec<Pid>> {
+        let mut children_of: HashMap<Pid, Vec<Pid>> = HashMap::new();
+        for &(parent, child) in edges {
+            children_of.entry(Pid::from_u32(parent)).or_default().push(Pid::from_u32(child));
+        }
+        children_of
+    }
+
+    #[test]
+    fn leaf_process_has_no_descendants() {
+        let children_of = children_map(&[]);
+        assert_eq!(descendants_of(&children_of, Pid::from_u32(1)), vec![]);
+    }
+
+    #[test]
+    fn descendants_are_ordered_deepest_generation_first() {
+        // 1 -> 2 -> 3, plus 1 -> 4
+        let children_of = children_map(&[(1, 2), (2, 3), (1, 4)]);
+        let result = descendants_of(&children_of, Pid::from_u32(1));
+
+        let pos = |pid: u32| result.iter().position(|&p| p == Pid::from_u32(pid)).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(pos(3) < pos(2), "grandchild 3 must come before its parent 2");
+        assert!(pos(3) < pos(4), "second generation (3) must come before first generation (4)");
+        assert!(pos(2) < pos(4) || pos(4) < pos(2)); // order within a generation is unspecified
+    }
+
+    #[test]
+    fn only_reachable_descendants_are_returned() {
+        let children_of = children_map(&[(1, 2), (99, 100)]);
+        let result = descendants_of(&children_of, Pid::from_u32(1));
+        assert_eq!(result, vec![Pid::from_u32(2)]);
+    }
+
+    #[test]
+    fn sum_memory_adds_root_and_every_descendant() {
+        assert_eq!(sum_memory(100, [10, 20, 30].into_iter()), 160);
+    }
+
+    #[test]
+    fn sum_memory_with_no_descendants_is_just_root() {
+        assert_eq!(sum_memory(100, std::iter::empty()), 100);
+    }
+}
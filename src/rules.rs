@@ -0,0 +1,355 @@
+//! Rule-file subsystem: monitoring many independent process groups, each
+//! with its own match criteria, limits, grace period, and signal chain,
+//! from a single daemon invocation.
+//!
+//! A rule file is either a JSON array of rule objects or a TOML document of
+//! `[[rules]]` tables; see [`RuleSpec`] for the fields each rule accepts. Exactly one of `process_name`,
+//! `process_name_regex`, or `cmdline_contains` must be set per rule.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use byte_unit::Byte;
+use log::{debug, warn};
+use regex::Regex;
+use serde::Deserialize;
+use sysinfo::{Pid, Process, System, Uid};
+
+use crate::{escalation, limits, proctree, signal_process, str_to_bytes_of_memory, ProcessReaperError};
+
+/// A single rule as read from a TOML/JSON rule file, before its strings are
+/// parsed into durations, signals, and a compiled matcher.
+#[derive(Debug, Deserialize)]
+pub struct RuleSpec {
+    /// Label used in log messages; defaults to the primary match criterion.
+    name: Option<String>,
+
+    process_name: Option<String>,
+    process_name_regex: Option<String>,
+    cmdline_contains: Option<String>,
+    user: Option<String>,
+
+    memory_limit: Option<String>,
+    virtual_memory_limit: Option<String>,
+    cpu_limit: Option<f32>,
+    io_write_rate_limit: Option<String>,
+
+    #[serde(default = "default_grace_period")]
+    grace_period: String,
+    #[serde(default = "default_term_signal")]
+    term_signal: String,
+    #[serde(default = "default_kill_signal")]
+    kill_signal: String,
+    /// Full escalation chain, one signal per grace period, e.g. `["INT", "QUIT", "KILL"]`;
+    /// overrides term_signal/kill_signal if given.
+    signal_chain: Option<Vec<String>>,
+
+    #[serde(default)]
+    kill_tree: bool,
+    #[serde(default)]
+    sum_tree: bool,
+}
+
+fn default_grace_period() -> String { "2s".to_string() }
+fn default_term_signal() -> String { "TERM".to_string() }
+fn default_kill_signal() -> String { "KILL".to_string() }
+
+/// What a rule matches a process against.
+enum Matcher {
+    Name(String),
+    NameRegex(Regex),
+    CmdlineContains(String),
+}
+
+impl Matcher {
+    fn matches(&self, process: &Process) -> bool {
+        match self {
+            Matcher::Name(name) => process.name().to_string_lossy() == name.as_str(),
+            Matcher::NameRegex(regex) => regex.is_match(&process.name().to_string_lossy()),
+            Matcher::CmdlineContains(needle) => {
+                let cmdline = process.cmd().iter()
+                    .map(|arg| arg.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                cmdline.contains(needle.as_str())
+            }
+        }
+    }
+}
+
+/// A fully-resolved, runtime-ready rule: its matcher and limits, plus the
+/// escalation state of every PID it has signaled so far.
+pub struct Rule {
+    name: String,
+    matcher: Matcher,
+    user: Option<Uid>,
+    limits: limits::Limits,
+    grace_period: Duration,
+    signal_chain: Vec<sysinfo::Signal>,
+    kill_tree: bool,
+    sum_tree: bool,
+    tracked: escalation::PidStates,
+    /// Descendant PIDs captured at the moment each tracked root was first
+    /// signaled, under `kill_tree`. Must be carried forward rather than
+    /// re-derived once the root exits, since orphaned children reparent to
+    /// init and drop out of `proctree::descendants(sys, root)`.
+    subtree_pids: HashMap<Pid, Vec<Pid>>,
+    io_samples: HashMap<Pid, (Instant, u64)>,
+    reaped_total: u64,
+}
+
+impl RuleSpec {
+    fn into_rule(self, sys: &System) -> Result<Rule, Box<dyn Error>> {
+        let matcher = match (self.process_name, self.process_name_regex, self.cmdline_contains) {
+            (Some(name), None, None) => Matcher::Name(name),
+            (None, Some(pattern), None) => Matcher::NameRegex(Regex::new(&pattern)?),
+            (None, None, Some(needle)) => Matcher::CmdlineContains(needle),
+            _ => return Err(Box::new(ProcessReaperError::new(
+                "Each rule must set exactly one of process_name, process_name_regex, or cmdline_contains"))),
+        };
+
+        let user = self.user.as_deref().map(|spec| resolve_uid(sys, spec)).transpose()?;
+
+        if self.memory_limit.is_none() && self.virtual_memory_limit.is_none()
+            && self.cpu_limit.is_none() && self.io_write_rate_limit.is_none() {
+            return Err(Box::new(ProcessReaperError::new(
+                "Each rule must set at least one of memory_limit, virtual_memory_limit, cpu_limit, or io_write_rate_limit")));
+        }
+
+        let limits = limits::Limits {
+            memory: self.memory_limit.as_deref().map(|s| str_to_bytes_of_memory(s, sys)).transpose()?,
+            virtual_memory: self.virtual_memory_limit.as_deref().map(|s| str_to_bytes_of_memory(s, sys)).transpose()?,
+            cpu_percent: self.cpu_limit,
+            io_write_rate: self.io_write_rate_limit.as_deref()
+                .map(|s| Byte::parse_str(s, true).map(|b| b.as_u64())).transpose()?,
+        };
+
+        let name = self.name.unwrap_or_else(|| match &matcher {
+            Matcher::Name(name) => name.clone(),
+            Matcher::NameRegex(regex) => regex.as_str().to_string(),
+            Matcher::CmdlineContains(needle) => needle.clone(),
+        });
+
+        Ok(Rule {
+            name,
+            matcher,
+            user,
+            limits,
+            grace_period: humantime::parse_duration(&self.grace_period)?,
+            signal_chain: match &self.signal_chain {
+                Some(chain) if !chain.is_empty() => chain.iter()
+                    .map(|s| escalation::parse_signal(s))
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => vec![escalation::parse_signal(&self.term_signal)?, escalation::parse_signal(&self.kill_signal)?],
+            },
+            kill_tree: self.kill_tree,
+            sum_tree: self.sum_tree,
+            tracked: escalation::PidStates::new(),
+            subtree_pids: HashMap::new(),
+            io_samples: HashMap::new(),
+            reaped_total: 0,
+        })
+    }
+}
+
+fn resolve_uid(sys: &System, spec: &str) -> Result<Uid, Box<dyn Error>> {
+    sys.users().iter()
+        .find(|user| user.name() == spec)
+        .map(|user| user.id().clone())
+        .ok_or_else(|| Box::new(ProcessReaperError::new(&format!("Unknown user in rule file: {}", spec))) as Box<dyn Error>)
+}
+
+impl Rule {
+    /// Builds a single rule directly from the legacy single-process CLI
+    /// flags, for use when no `--config` file is given.
+    pub fn from_single_process_args(
+        process_name: String,
+        memory_limit: &str,
+        virtual_memory_limit: Option<&str>,
+        cpu_limit: Option<f32>,
+        io_write_rate_limit: Option<&str>,
+        grace_period: Duration,
+        signal_chain: Vec<sysinfo::Signal>,
+        kill_tree: bool,
+        sum_tree: bool,
+        sys: &System,
+    ) -> Result<Rule, Box<dyn Error>> {
+        let limits = limits::Limits {
+            memory: Some(str_to_bytes_of_memory(memory_limit, sys)?),
+            virtual_memory: virtual_memory_limit.map(|s| str_to_bytes_of_memory(s, sys)).transpose()?,
+            cpu_percent: cpu_limit,
+            io_write_rate: io_write_rate_limit
+                .map(|s| Byte::parse_str(s, true).map(|b| b.as_u64())).transpose()?,
+        };
+
+        Ok(Rule {
+            matcher: Matcher::Name(process_name.clone()),
+            name: process_name,
+            user: None,
+            limits,
+            grace_period,
+            signal_chain,
+            kill_tree,
+            sum_tree,
+            tracked: escalation::PidStates::new(),
+            subtree_pids: HashMap::new(),
+            io_samples: HashMap::new(),
+            reaped_total: 0,
+        })
+    }
+
+    fn matching_processes<'a>(&self, sys: &'a System) -> Vec<&'a Process> {
+        sys.processes().values()
+            .filter(|process| self.matcher.matches(process))
+            .filter(|process| self.user.as_ref().map_or(true, |uid| process.user_id() == Some(uid)))
+            .collect()
+    }
+
+    /// Scans for newly-offending processes matching this rule and signals
+    /// (and starts tracking) any that breach a configured limit.
+    pub fn scan(&mut self, sys: &System) {
+        for process in self.matching_processes(sys) {
+            let pid = process.pid();
+            if self.tracked.contains_key(&pid) {
+                continue;
+            }
+
+            let effective_memory = if self.sum_tree {
+                proctree::subtree_memory(sys, pid)
+            } else {
+                process.memory()
+            };
+
+            if let Some(breach) = self.limits.check(process, effective_memory, &mut self.io_samples) {
+                warn!("[{}] {} ({}) breached {} limit ({}); terminating",
+                    self.name, process.name().to_string_lossy(), pid, breach.metric, breach.message);
+
+                let subtree = if self.kill_tree { proctree::descendants(sys, pid) } else { Vec::new() };
+                signal_process(sys, &self.name, pid, &subtree, self.signal_chain[0]);
+                self.tracked.insert(pid, escalation::PidState::new(self.signal_chain.clone(), Instant::now()));
+                if self.kill_tree {
+                    self.subtree_pids.insert(pid, subtree);
+                }
+            }
+        }
+
+        self.io_samples.retain(|pid, _| sys.process(*pid).is_some());
+    }
+
+    /// The earliest time any currently-tracked PID under this rule is next
+    /// due for escalation. PIDs whose signal chain is already exhausted have
+    /// nothing left to escalate to, so they're excluded here — otherwise an
+    /// unkillable process (e.g. stuck in D-state) would pin this to a fixed
+    /// past instant forever and busy-loop the main loop.
+    pub fn earliest_deadline(&self) -> Option<Instant> {
+        self.tracked.values()
+            .filter(|state| !state.is_exhausted())
+            .map(|state| state.last_signal_at() + self.grace_period)
+            .min()
+    }
+
+    pub fn tracked_pids(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.tracked.keys().copied()
+    }
+
+    /// Drops PIDs whose whole subtree (root plus, under `kill_tree`, every
+    /// descendant captured at signal time) has exited, and escalates any
+    /// still-alive ones whose grace period has elapsed to the next signal
+    /// in the chain.
+    pub fn escalate_and_reap(&mut self, sys: &System) {
+        let now = Instant::now();
+        let name = &self.name;
+        let grace_period = self.grace_period;
+        let subtree_pids = &self.subtree_pids;
+        let no_subtree = Vec::new();
+
+        let reaped_total = &mut self.reaped_total;
+        self.tracked.retain(|&pid, state| {
+            let subtree = subtree_pids.get(&pid).unwrap_or(&no_subtree);
+            let root_alive = sys.process(pid).is_some();
+            let subtree_alive = subtree.iter().any(|&descendant_pid| sys.process(descendant_pid).is_some());
+
+            if !root_alive && !subtree_alive {
+                debug!("[{}] {} reaped", name, pid);
+                *reaped_total += 1;
+                return false;
+            }
+
+            if now >= state.last_signal_at() + grace_period {
+                if let Some(next_signal) = state.escalate(now) {
+                    warn!("[{}] {} still alive after grace period; escalating to {:?}", name, pid, next_signal);
+                    signal_process(sys, name, pid, subtree, next_signal);
+                }
+            }
+
+            true
+        });
+
+        let tracked = &self.tracked;
+        self.subtree_pids.retain(|pid, _| tracked.contains_key(pid));
+    }
+
+    pub fn status_summary(&self) -> String {
+        format!("{}: {} over limit, {} reaped total", self.name, self.tracked.len(), self.reaped_total)
+    }
+}
+
+/// Carries forward escalation state, I/O rate samples, and the reap counter
+/// from `previous` into `new_rules` for any rule that still exists (matched
+/// by name), so a SIGHUP reload doesn't abandon a PID that's mid-escalation
+/// or reset the running "reaped total" stat.
+pub fn carry_over_state(new_rules: &mut [Rule], previous: Vec<Rule>) {
+    let mut previous_by_name: HashMap<String, Rule> = previous.into_iter()
+        .map(|rule| (rule.name.clone(), rule))
+        .collect();
+
+    for new_rule in new_rules.iter_mut() {
+        if let Some(old_rule) = previous_by_name.remove(&new_rule.name) {
+            new_rule.tracked = old_rule.tracked;
+            new_rule.subtree_pids = old_rule.subtree_pids;
+            new_rule.io_samples = old_rule.io_samples;
+            new_rule.reaped_total = old_rule.reaped_total;
+        }
+    }
+}
+
+/// Loads a list of rules from a TOML or JSON file, detected by extension
+/// (defaulting to TOML if the extension is missing or unrecognized).
+///
+/// Rule names (explicit or defaulted from the match criterion) must be
+/// unique: they're the key [`carry_over_state`] uses to reattach escalation
+/// state across a SIGHUP reload, and two rules sharing a name would make
+/// that reattachment ambiguous.
+pub fn load_rules(path: &Path, sys: &System) -> Result<Vec<Rule>, Box<dyn Error>> {
+    // A TOML document is always a table at the top level, so there the list
+    // of rules lives under a `rules` key, written as `[[rules]]` tables.
+    #[derive(Deserialize)]
+    struct TomlRuleFile {
+        #[serde(default)]
+        rules: Vec<RuleSpec>,
+    }
+
+    let contents = std::fs::read_to_string(path)?;
+    let specs: Vec<RuleSpec> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&contents)?,
+        _ => toml::from_str::<TomlRuleFile>(&contents)?.rules,
+    };
+
+    if specs.is_empty() {
+        return Err(Box::new(ProcessReaperError::new("Rule file contains no rules")));
+    }
+
+    let rules: Vec<Rule> = specs.into_iter().map(|spec| spec.into_rule(sys)).collect::<Result<_, _>>()?;
+
+    let mut seen_names = std::collections::HashSet::new();
+    for rule in &rules {
+        if !seen_names.insert(rule.name.as_str()) {
+            return Err(Box::new(ProcessReaperError::new(&format!(
+                "Duplicate rule name '{}': set an explicit `name` on each rule so they're unambiguous", rule.name))));
+        }
+    }
+
+    Ok(rules)
+}